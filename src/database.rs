@@ -1,109 +1,430 @@
-use super::{Flag, FLAG_STATUS};
+use super::{Flag, ImportRecord, FLAG_STATUS};
 
-use postgres;
-use rusqlite;
-use std::collections::HashSet;
+use async_trait::async_trait;
+use deadpool_postgres::{Manager, ManagerConfig, Pool as PgPool, RecyclingMethod};
+use deadpool_sqlite::{Config as SqliteConfig, Pool as SqlitePool, Runtime};
+use futures::{stream, FutureExt, StreamExt};
 use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_postgres::{AsyncMessage, NoTls};
+
+// Error type shared by both backends. The pools and their drivers return
+// different concrete errors (`tokio_postgres::Error`, `rusqlite::Error`, the
+// deadpool pool/interact errors), so we box them behind the standard trait and
+// let `?` unify them. `run()`/`main_loop()` stay generic over `T: Error`.
+pub type DbError = Box<dyn std::error::Error + Send + Sync>;
+
+// Current schema version understood by this binary. Bump it and add a matching
+// step to the migration ladder (`migrate_sqlite` / `Postgres::migrate`) whenever
+// the `flags` schema changes, so existing databases are upgraded on startup
+// instead of silently skipping the new columns.
+pub const DB_VERSION: u32 = 3;
 
 // Queries
 // Crete table flags(id, flag, group_id, status, received_time, sent_time) the
 // received_time/sent_time is set automatically throw a SQL query and is not
 // used by the application but only for debug
-const FLAG_TABLE_SQLITE: &str = "CREATE TABLE IF NOT EXISTS flags 
+const FLAG_TABLE_SQLITE: &str = "CREATE TABLE IF NOT EXISTS flags
     (id INTEGER PRIMARY KEY, flag TEXT NOT NULL UNIQUE, group_id INT NOT NULL,
     status INT2 NOT NULL DEFAULT 0 CHECK (status < 4),
     received_time TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP, sent_time TEXT)";
-const FLAG_TABLE_POSTGRESQL: &str = "CREATE TABLE IF NOT EXISTS flags 
-    (id SERIAL PRIMARY KEY, flag TEXT NOT NULL UNIQUE, group_id INT NOT NULL,
+const FLAG_TABLE_POSTGRESQL: &str = "CREATE TABLE IF NOT EXISTS flags
+    (id BIGSERIAL PRIMARY KEY, flag TEXT NOT NULL UNIQUE, group_id INT NOT NULL,
     status INT2 NOT NULL DEFAULT 0 CHECK (status < 4),
     received_time TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP, sent_time TEXT)";
-// Get all the flags with status unsent
-const SELECT_UNSENT: &str = "SELECT id, flag, group_id, status FROM flags WHERE status = 0";
+// Dedicated table tracking the applied schema version on the PostgreSQL backend
+// (SQLite uses the built-in `user_version` pragma instead).
+const SCHEMA_VERSION_TABLE_POSTGRESQL: &str =
+    "CREATE TABLE IF NOT EXISTS schema_version (version INT NOT NULL)";
+// Read the stored PostgreSQL schema version
+const SELECT_SCHEMA_VERSION: &str = "SELECT version FROM schema_version";
+// Clear and (re)insert the stored PostgreSQL schema version
+const CLEAR_SCHEMA_VERSION: &str = "DELETE FROM schema_version";
+const UPDATE_SCHEMA_VERSION: &str = "INSERT INTO schema_version (version) VALUES ($1)";
+// Channel name used for the PostgreSQL LISTEN/NOTIFY submission wakeup
+const NOTIFY_CHANNEL: &str = "new_flags";
+// Install an AFTER INSERT trigger that notifies `new_flags` with the new id so
+// the listener can trigger submission immediately instead of waiting for the
+// next interval tick. PostgreSQL-only; SQLite has no equivalent.
+const FLAG_NOTIFY_TRIGGER_POSTGRESQL: &str = "
+    CREATE OR REPLACE FUNCTION notify_new_flags() RETURNS trigger AS $$
+    BEGIN
+        PERFORM pg_notify('new_flags', NEW.id::text);
+        RETURN NEW;
+    END;
+    $$ LANGUAGE plpgsql;
+    DROP TRIGGER IF EXISTS flags_notify ON flags;
+    CREATE TRIGGER flags_notify AFTER INSERT ON flags
+        FOR EACH ROW EXECUTE PROCEDURE notify_new_flags();";
+// Add the retry bookkeeping columns for requeued flags (epoch seconds)
+const ADD_RETRY_COLUMNS_SQLITE: &str = "
+    ALTER TABLE flags ADD COLUMN retries INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE flags ADD COLUMN next_attempt INTEGER NOT NULL DEFAULT 0;";
+const ADD_RETRY_COLUMNS_POSTGRESQL: &str = "
+    ALTER TABLE flags ADD COLUMN retries INT NOT NULL DEFAULT 0;
+    ALTER TABLE flags ADD COLUMN next_attempt BIGINT NOT NULL DEFAULT 0;";
+// Get all the flags ready to send: freshly unsent, or requeued flags whose
+// backoff window has elapsed.
+const SELECT_UNSENT_SQLITE: &str = "SELECT id, flag, group_id, status FROM flags
+    WHERE status = 0 OR (status = 3 AND next_attempt <= strftime('%s', 'now'))";
+const SELECT_UNSENT_POSTGRESQL: &str = "SELECT id, flag, group_id, status FROM flags
+    WHERE status = 0 OR (status = 3 AND next_attempt <= extract(epoch from now())::bigint)";
 // Set the flag status to sent and update sent_time stamp
-const UPDATE_SENT: &str = "UPDATE flags SET status = 1,
+const UPDATE_SENT_SQLITE: &str = "UPDATE flags SET status = 1,
     sent_time = CURRENT_TIMESTAMP WHERE id = ?";
+const UPDATE_SENT_POSTGRESQL: &str = "UPDATE flags SET status = 1,
+    sent_time = CURRENT_TIMESTAMP WHERE id = $1";
 // Set the flag status to invalid
-const UPDATE_INVALID: &str = "UPDATE flags SET status = 2 WHERE id = ?";
+const UPDATE_INVALID_SQLITE: &str = "UPDATE flags SET status = 2 WHERE id = ?";
+const UPDATE_INVALID_POSTGRESQL: &str = "UPDATE flags SET status = 2 WHERE id = $1";
+// Requeue a flag with exponential backoff: the n-th retry waits base * 2^(n-1)
+// seconds. `retries` is read before the increment, so it is the exponent; the
+// shift amount is capped so it can never overflow the 64-bit column type and
+// collapse `next_attempt` back to `<= now`.
+const UPDATE_RETRY_SQLITE: &str = "UPDATE flags SET status = 3, retries = retries + 1,
+    next_attempt = strftime('%s', 'now') + ? * (1 << min(retries, 20)) WHERE id = ?";
+const UPDATE_RETRY_POSTGRESQL: &str = "UPDATE flags SET status = 3, retries = retries + 1,
+    next_attempt = extract(epoch from now())::bigint + $1::bigint * (1 << least(retries, 20)) WHERE id = $2";
+// Idempotent bulk import insert, skipping flags already present
+const INSERT_FLAG_SQLITE: &str = "INSERT OR IGNORE INTO flags (flag, group_id) VALUES (?, ?)";
+const INSERT_FLAG_POSTGRESQL: &str =
+    "INSERT INTO flags (flag, group_id) VALUES ($1, $2) ON CONFLICT(flag) DO NOTHING";
 
 pub struct Sqlite {
-    pub db: rusqlite::Connection,
+    pub pool: SqlitePool,
 }
 
 pub struct Postgres {
-    pub db: postgres::Client,
+    pub pool: PgPool,
+    // Raw connection string, kept so the LISTEN/NOTIFY listener can open its own
+    // dedicated connection outside the pool.
+    config: String,
 }
 
+impl Sqlite {
+    // Open a pooled SQLite connection manager. rusqlite is synchronous, so
+    // deadpool runs every `interact()` closure on a `spawn_blocking` thread and
+    // keeps a warm pool of connections across ticks.
+    pub fn open(path: &str) -> Result<Self, DbError> {
+        let pool = SqliteConfig::new(path).create_pool(Runtime::Tokio1)?;
+        Ok(Sqlite { pool })
+    }
+
+    // Read the schema version stored in the `user_version` pragma (0 on a fresh
+    // database).
+    pub async fn curr_db_version(&self) -> Result<u32, DbError> {
+        let conn = self.pool.get().await?;
+        let version = conn.interact(|conn| curr_sqlite_version(conn)).await??;
+        Ok(version)
+    }
+}
+
+impl Postgres {
+    // Build an async, pooled `tokio-postgres` client. The pool is held for the
+    // whole lifetime of the submitter so ticks reuse warm connections instead
+    // of reconnecting.
+    pub fn connect(config: &str) -> Result<Self, DbError> {
+        let pg_config: tokio_postgres::Config = config.parse()?;
+        let mgr_config = ManagerConfig {
+            recycling_method: RecyclingMethod::Fast,
+        };
+        let manager = Manager::from_config(pg_config, NoTls, mgr_config);
+        let pool = PgPool::builder(manager).build()?;
+        Ok(Postgres {
+            pool,
+            config: config.to_string(),
+        })
+    }
+
+    // Read the schema version currently stored in the database, defaulting to 0
+    // when the dedicated `schema_version` table has not been created yet.
+    pub async fn curr_db_version(&self) -> Result<u32, DbError> {
+        let client = self.pool.get().await?;
+        client
+            .execute(SCHEMA_VERSION_TABLE_POSTGRESQL, &[])
+            .await?;
+        let version: i32 = client
+            .query_opt(SELECT_SCHEMA_VERSION, &[])
+            .await?
+            .map(|row| row.get(0))
+            .unwrap_or(0);
+        Ok(version as u32)
+    }
+
+    // Apply every pending migration step, each inside its own transaction, and
+    // bump the stored version. Fails loudly if the database was written by a
+    // newer binary than this one.
+    async fn migrate(&self) -> Result<(), DbError> {
+        let version = self.curr_db_version().await?;
+        if version > DB_VERSION {
+            return Err(format!(
+                "database schema version {} is newer than this binary ({})",
+                version, DB_VERSION
+            )
+            .into());
+        }
+        if version < 1 {
+            let mut client = self.pool.get().await?;
+            let transaction = client.transaction().await?;
+            transaction.batch_execute(FLAG_TABLE_POSTGRESQL).await?;
+            transaction.execute(CLEAR_SCHEMA_VERSION, &[]).await?;
+            transaction.execute(UPDATE_SCHEMA_VERSION, &[&1i32]).await?;
+            transaction.commit().await?;
+            println!("[SETUP] Applied PostgreSQL migration to version 1");
+        }
+        if version < 2 {
+            let mut client = self.pool.get().await?;
+            let transaction = client.transaction().await?;
+            transaction
+                .batch_execute(FLAG_NOTIFY_TRIGGER_POSTGRESQL)
+                .await?;
+            transaction.execute(CLEAR_SCHEMA_VERSION, &[]).await?;
+            transaction.execute(UPDATE_SCHEMA_VERSION, &[&2i32]).await?;
+            transaction.commit().await?;
+            println!("[SETUP] Applied PostgreSQL migration to version 2");
+        }
+        if version < 3 {
+            let mut client = self.pool.get().await?;
+            let transaction = client.transaction().await?;
+            transaction
+                .batch_execute(ADD_RETRY_COLUMNS_POSTGRESQL)
+                .await?;
+            transaction.execute(CLEAR_SCHEMA_VERSION, &[]).await?;
+            transaction.execute(UPDATE_SCHEMA_VERSION, &[&3i32]).await?;
+            transaction.commit().await?;
+            println!("[SETUP] Applied PostgreSQL migration to version 3");
+        }
+        Ok(())
+    }
+
+    // Hold a dedicated connection issuing `LISTEN new_flags` and forward each
+    // notification (coalescing bursts through a capacity-1 channel) so the run
+    // loop can submit new flags immediately instead of waiting for the tick.
+    pub async fn listen(&self) -> Result<mpsc::Receiver<()>, DbError> {
+        let (client, mut connection) = tokio_postgres::connect(&self.config, NoTls).await?;
+
+        // Capacity-1 channel: a burst of NOTIFYs collapses into a single pending
+        // wakeup, so `run()` fires once per burst rather than once per flag.
+        let (tx, rx) = mpsc::channel(1);
+
+        tokio::spawn(async move {
+            // The split connection must be polled for notifications to arrive,
+            // so drive it and the LISTEN query concurrently. `client` is moved in
+            // and kept alive for the whole task, keeping the connection open.
+            let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx)).fuse();
+            let listen = client.batch_execute("LISTEN new_flags").fuse();
+            futures::pin_mut!(listen);
+            let mut listening = false;
+            loop {
+                tokio::select! {
+                    res = &mut listen, if !listening => {
+                        listening = true;
+                        if let Err(err) = res {
+                            eprintln!("[ERROR][LISTEN] {}", err);
+                            return;
+                        }
+                        println!("[NOTIFY] Listening on channel {}", NOTIFY_CHANNEL);
+                    }
+                    msg = messages.next() => match msg {
+                        Some(Ok(AsyncMessage::Notification(note))) => {
+                            println!("[NOTIFY] new flag id {}", note.payload());
+                            // Drop the signal if one is already pending (coalescing)
+                            let _ = tx.try_send(());
+                        }
+                        Some(Ok(_)) => {}
+                        Some(Err(err)) => {
+                            eprintln!("[ERROR][LISTEN] {}", err);
+                            break;
+                        }
+                        None => break,
+                    }
+                }
+            }
+        });
+
+        Ok(rx)
+    }
+}
+
+// Read the schema version stored in SQLite's `user_version` pragma (0 on a
+// fresh database).
+fn curr_sqlite_version(conn: &rusqlite::Connection) -> rusqlite::Result<u32> {
+    conn.query_row("PRAGMA user_version", rusqlite::params![], |row| {
+        let version: i64 = row.get(0)?;
+        Ok(version as u32)
+    })
+}
+
+// Apply every pending migration step inside a transaction and bump the stored
+// `user_version`. Fails loudly with a typed error if the database is newer than
+// this binary, matching the PostgreSQL backend.
+fn migrate_sqlite(conn: &mut rusqlite::Connection) -> Result<(), DbError> {
+    let version = curr_sqlite_version(conn)?;
+    if version > DB_VERSION {
+        return Err(format!(
+            "database schema version {} is newer than this binary ({})",
+            version, DB_VERSION
+        )
+        .into());
+    }
+    if version < 1 {
+        let transaction = conn.transaction()?;
+        transaction.execute_batch(FLAG_TABLE_SQLITE)?;
+        // Pragmas cannot be parametrized, so the version literal is inlined.
+        transaction.execute_batch("PRAGMA user_version = 1")?;
+        transaction.commit()?;
+        println!("[SETUP] Applied SQLite migration to version 1");
+    }
+    if version < 2 {
+        // Version 2 adds the PostgreSQL NOTIFY trigger; SQLite has no equivalent,
+        // so the step only bumps the stored version to stay in lockstep.
+        conn.execute_batch("PRAGMA user_version = 2")?;
+        println!("[SETUP] Applied SQLite migration to version 2");
+    }
+    if version < 3 {
+        let transaction = conn.transaction()?;
+        transaction.execute_batch(ADD_RETRY_COLUMNS_SQLITE)?;
+        transaction.execute_batch("PRAGMA user_version = 3")?;
+        transaction.commit()?;
+        println!("[SETUP] Applied SQLite migration to version 3");
+    }
+    Ok(())
+}
+
+#[async_trait]
 pub trait Database<T> {
-    fn setup(&mut self) -> Result<(), T>;
-    fn get_unsent_flags(&mut self) -> Result<Vec<Arc<Flag>>, T>;
-    fn set_sent_flags(&mut self, sent_set: &mut HashSet<i64>) -> Result<(), T>;
-    fn set_invalid_flags(&mut self, invalid_set: &mut HashSet<i64>) -> Result<(), T>;
+    async fn setup(&self) -> Result<(), T>;
+    async fn get_unsent_flags(&self) -> Result<Vec<Arc<Flag>>, T>;
+    async fn set_sent_flags(&self, ids: &[i64]) -> Result<(), T>;
+    async fn set_invalid_flags(&self, ids: &[i64]) -> Result<(), T>;
+    // Requeue each flag for a later attempt with exponential backoff, where
+    // `base` is the backoff base in seconds.
+    async fn set_retry_flags(&self, ids: &[i64], base: u32) -> Result<(), T>;
+    // Insert a batch of imported flags in a single transaction, returning the
+    // number actually inserted (duplicates are skipped).
+    async fn import_flags(&self, records: &[ImportRecord]) -> Result<usize, T>;
 }
 
-impl Database<rusqlite::Error> for Sqlite {
-    fn setup(&mut self) -> rusqlite::Result<()> {
-        println!("[SETUP] Creating SQLite tables");
-        // Create table flag
-        &self.db.execute_batch(FLAG_TABLE_SQLITE)?;
+#[async_trait]
+impl Database<DbError> for Sqlite {
+    async fn setup(&self) -> Result<(), DbError> {
+        println!("[SETUP] Migrating SQLite schema");
+        let conn = self.pool.get().await?;
+        // Run every pending migration step up to DB_VERSION
+        conn.interact(|conn| migrate_sqlite(conn)).await??;
         Ok(())
     }
 
-    fn get_unsent_flags(&mut self) -> rusqlite::Result<Vec<Arc<Flag>>> {
-        // Prepare query for select unsent flags
-        let mut prepare = self.db.prepare(SELECT_UNSENT)?;
-        // Map return to Flag struct
-        let flags: Vec<Arc<Flag>> = prepare
-            .query_map(rusqlite::params![], |row| {
-                let status: i32 = row.get(3)?;
-                Ok(Arc::new(Flag {
-                    id: row.get(0)?,
-                    flag: row.get(1)?,
-                    group: row.get(2)?,
-                    status: FLAG_STATUS[status as usize],
-                }))
-            })?
-            .map(|x| x.unwrap())
-            .collect();
+    async fn get_unsent_flags(&self) -> Result<Vec<Arc<Flag>>, DbError> {
+        let conn = self.pool.get().await?;
+        // Prepare query for select unsent flags and map return to Flag struct
+        let flags = conn
+            .interact(|conn| {
+                let mut prepare = conn.prepare(SELECT_UNSENT_SQLITE)?;
+                let flags: Vec<Arc<Flag>> = prepare
+                    .query_map(rusqlite::params![], |row| {
+                        let status: i32 = row.get(3)?;
+                        Ok(Arc::new(Flag {
+                            id: row.get(0)?,
+                            flag: row.get(1)?,
+                            group: row.get(2)?,
+                            status: FLAG_STATUS[status as usize],
+                        }))
+                    })?
+                    .map(|x| x.unwrap())
+                    .collect();
+                Ok::<_, rusqlite::Error>(flags)
+            })
+            .await??;
         println!("[GET] flags: {:#?}", flags);
         Ok(flags)
     }
 
-    fn set_sent_flags(&mut self, flag_set: &mut HashSet<i64>) -> rusqlite::Result<()> {
-        let transaction = self.db.transaction()?;
-        for id in flag_set.drain() {
-            println!("[SET] Set flag with id {} as sent", id);
-            // Set the flag with the id to sent
-            transaction.execute(UPDATE_SENT, rusqlite::params![id])?;
-        }
-        transaction.commit()?;
+    async fn set_sent_flags(&self, ids: &[i64]) -> Result<(), DbError> {
+        let ids = ids.to_vec();
+        let conn = self.pool.get().await?;
+        conn.interact(move |conn| {
+            let transaction = conn.transaction()?;
+            for id in ids {
+                println!("[SET] Set flag with id {} as sent", id);
+                // Set the flag with the id to sent
+                transaction.execute(UPDATE_SENT_SQLITE, rusqlite::params![id])?;
+            }
+            transaction.commit()
+        })
+        .await??;
         Ok(())
     }
 
-    fn set_invalid_flags(&mut self, invalid_set: &mut HashSet<i64>) -> rusqlite::Result<()> {
-        let transaction = self.db.transaction()?;
-        for id in invalid_set.drain() {
-            println!("[SET] Set flag with id {} as invalid", id);
-            // Set the flag with the id to sent
-            transaction.execute(UPDATE_INVALID, rusqlite::params![id])?;
-        }
-        transaction.commit()?;
+    async fn set_invalid_flags(&self, ids: &[i64]) -> Result<(), DbError> {
+        let ids = ids.to_vec();
+        let conn = self.pool.get().await?;
+        conn.interact(move |conn| {
+            let transaction = conn.transaction()?;
+            for id in ids {
+                println!("[SET] Set flag with id {} as invalid", id);
+                // Set the flag with the id to invalid
+                transaction.execute(UPDATE_INVALID_SQLITE, rusqlite::params![id])?;
+            }
+            transaction.commit()
+        })
+        .await??;
+        Ok(())
+    }
+
+    async fn set_retry_flags(&self, ids: &[i64], base: u32) -> Result<(), DbError> {
+        let base = base as i64;
+        let ids = ids.to_vec();
+        let conn = self.pool.get().await?;
+        conn.interact(move |conn| {
+            let transaction = conn.transaction()?;
+            for id in ids {
+                println!("[SET] Requeue flag with id {} for retry", id);
+                // Requeue the flag with the id with exponential backoff
+                transaction.execute(UPDATE_RETRY_SQLITE, rusqlite::params![base, id])?;
+            }
+            transaction.commit()
+        })
+        .await??;
         Ok(())
     }
+
+    async fn import_flags(&self, records: &[ImportRecord]) -> Result<usize, DbError> {
+        let records = records.to_vec();
+        let conn = self.pool.get().await?;
+        let inserted = conn
+            .interact(move |conn| {
+                let transaction = conn.transaction()?;
+                let mut inserted = 0usize;
+                for record in &records {
+                    inserted += transaction.execute(
+                        INSERT_FLAG_SQLITE,
+                        rusqlite::params![record.flag, record.group],
+                    )?;
+                }
+                transaction.commit()?;
+                Ok::<_, rusqlite::Error>(inserted)
+            })
+            .await??;
+        Ok(inserted)
+    }
 }
 
-impl Database<postgres::Error> for Postgres {
-    fn setup(&mut self) -> Result<(), postgres::Error> {
-        println!("[SETUP] Creating SQLite tables");
-        // Create table flag
-        &self.db.execute(FLAG_TABLE_POSTGRESQL, &[])?;
+#[async_trait]
+impl Database<DbError> for Postgres {
+    async fn setup(&self) -> Result<(), DbError> {
+        println!("[SETUP] Migrating PostgreSQL schema");
+        // Run every pending migration step up to DB_VERSION
+        self.migrate().await?;
         Ok(())
     }
 
-    fn get_unsent_flags(&mut self) -> Result<Vec<Arc<Flag>>, postgres::Error> {
+    async fn get_unsent_flags(&self) -> Result<Vec<Arc<Flag>>, DbError> {
+        let client = self.pool.get().await?;
         // Map return to Flag struct
-        let flags: Vec<Arc<Flag>> = self
-            .db
-            .query(SELECT_UNSENT, &[])?
+        let flags: Vec<Arc<Flag>> = client
+            .query(SELECT_UNSENT_POSTGRESQL, &[])
+            .await?
             .iter()
             .map(|row| {
                 let status: i32 = row.get(3);
@@ -119,24 +440,53 @@ impl Database<postgres::Error> for Postgres {
         Ok(flags)
     }
 
-    fn set_sent_flags(&mut self, sent_set: &mut HashSet<i64>) -> Result<(), postgres::Error> {
-        let mut transaction = self.db.transaction()?;
-        for id in sent_set.drain() {
+    async fn set_sent_flags(&self, ids: &[i64]) -> Result<(), DbError> {
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+        for id in ids {
             println!("[SET] Set flag with id {} as sent", id);
             // Set the flag with the id to sent
-            transaction.execute(UPDATE_SENT, &[&id])?;
+            transaction.execute(UPDATE_SENT_POSTGRESQL, &[id]).await?;
         }
-        transaction.commit()?;
+        transaction.commit().await?;
         Ok(())
     }
-    fn set_invalid_flags(&mut self, invalid_set: &mut HashSet<i64>) -> Result<(), postgres::Error> {
-        let mut transaction = self.db.transaction()?;
-        for id in invalid_set.drain() {
-            println!("[SET] Set flag with id {} as sent", id);
-            // Set the flag with the id to sent
-            transaction.execute(UPDATE_INVALID, &[&id])?;
+
+    async fn set_invalid_flags(&self, ids: &[i64]) -> Result<(), DbError> {
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+        for id in ids {
+            println!("[SET] Set flag with id {} as invalid", id);
+            // Set the flag with the id to invalid
+            transaction.execute(UPDATE_INVALID_POSTGRESQL, &[id]).await?;
         }
-        transaction.commit()?;
+        transaction.commit().await?;
+        Ok(())
+    }
+
+    async fn set_retry_flags(&self, ids: &[i64], base: u32) -> Result<(), DbError> {
+        let base = base as i64;
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+        for id in ids {
+            println!("[SET] Requeue flag with id {} for retry", id);
+            // Requeue the flag with the id with exponential backoff
+            transaction.execute(UPDATE_RETRY_POSTGRESQL, &[&base, id]).await?;
+        }
+        transaction.commit().await?;
         Ok(())
     }
+
+    async fn import_flags(&self, records: &[ImportRecord]) -> Result<usize, DbError> {
+        let mut client = self.pool.get().await?;
+        let transaction = client.transaction().await?;
+        let statement = transaction.prepare(INSERT_FLAG_POSTGRESQL).await?;
+        let mut inserted = 0usize;
+        for record in records {
+            inserted +=
+                transaction.execute(&statement, &[&record.flag, &record.group]).await? as usize;
+        }
+        transaction.commit().await?;
+        Ok(inserted)
+    }
 }
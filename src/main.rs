@@ -1,14 +1,17 @@
 use clap::{crate_description, crate_name, crate_version, value_t_or_exit, App, Arg};
-use postgres;
+use regex::Regex;
 use reqwest::{Client, Response};
-use rusqlite;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::error::Error;
 use std::fmt;
 use std::fs::File;
 use std::io::prelude::*;
+use std::io::BufReader;
+use std::sync::mpsc as std_mpsc;
 use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
 use tokio::task::{spawn, JoinHandle};
 use tokio::time::{interval, Duration};
 use toml;
@@ -25,6 +28,49 @@ pub struct Config {
     check_interval: u8,
     flags_quota: u8,
     single_run: Option<bool>,
+    #[serde(default)]
+    import: Option<String>,
+    #[serde(default = "default_retry_base")]
+    retry_base: u32,
+    #[serde(default)]
+    classification: Classification,
+    #[serde(default)]
+    protocol: Protocol,
+}
+
+// Base backoff in seconds: the n-th retry waits `retry_base * 2^(n-1)` seconds.
+fn default_retry_base() -> u32 {
+    5
+}
+
+// Submission backend. `Form` keeps the per-flag form POST; `BatchJson` groups a
+// whole quota chunk into a single JSON array POST and reads a per-flag verdict
+// array back.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Protocol {
+    Form,
+    BatchJson,
+}
+
+impl Default for Protocol {
+    fn default() -> Self {
+        Protocol::Form
+    }
+}
+
+// A single per-flag verdict returned by a batch-submission scoreboard.
+#[derive(Debug, Deserialize)]
+pub struct BatchVerdict {
+    flag: String,
+    #[serde(default)]
+    msg: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ImportRecord {
+    flag: String,
+    group: i32,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -32,10 +78,15 @@ pub enum FlagStatus {
     Unsent = 0,
     Sent = 1,
     Invalid = 2,
+    Retry = 3,
 }
 
-pub const FLAG_STATUS: [FlagStatus; 3] =
-    [FlagStatus::Unsent, FlagStatus::Sent, FlagStatus::Invalid];
+pub const FLAG_STATUS: [FlagStatus; 4] = [
+    FlagStatus::Unsent,
+    FlagStatus::Sent,
+    FlagStatus::Invalid,
+    FlagStatus::Retry,
+];
 
 impl fmt::Display for FlagStatus {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -43,6 +94,63 @@ impl fmt::Display for FlagStatus {
             FlagStatus::Unsent => write!(f, "{}", "unsent"),
             FlagStatus::Sent => write!(f, "{}", "sent"),
             FlagStatus::Invalid => write!(f, "{}", "invalid"),
+            FlagStatus::Retry => write!(f, "{}", "retry"),
+        }
+    }
+}
+
+// Outcome of classifying a scoring-server response. Retryable responses are
+// requeued with exponential backoff rather than discarded as invalid.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Outcome {
+    Accepted,
+    Invalid,
+    Retry,
+}
+
+// User-supplied patterns mapping response bodies to outcomes. Patterns are
+// regular expressions; a response matching a `retry` pattern is requeued, an
+// `invalid` one is dropped, and an `accepted` one is marked sent.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Classification {
+    #[serde(default)]
+    accepted: Vec<String>,
+    #[serde(default)]
+    invalid: Vec<String>,
+    #[serde(default)]
+    retry: Vec<String>,
+}
+
+// Compiled form of `Classification`, built once and shared across send tasks.
+pub struct Matcher {
+    accepted: Vec<Regex>,
+    invalid: Vec<Regex>,
+    retry: Vec<Regex>,
+}
+
+impl Matcher {
+    fn build(classification: &Classification) -> Result<Matcher, regex::Error> {
+        let compile = |patterns: &[String]| -> Result<Vec<Regex>, regex::Error> {
+            patterns.iter().map(|p| Regex::new(p)).collect()
+        };
+        Ok(Matcher {
+            accepted: compile(&classification.accepted)?,
+            invalid: compile(&classification.invalid)?,
+            retry: compile(&classification.retry)?,
+        })
+    }
+
+    // Classify a response body, returning `None` when no configured pattern
+    // matches so the caller can fall back to the legacy behavior.
+    fn classify(&self, text: &str) -> Option<Outcome> {
+        if self.retry.iter().any(|r| r.is_match(text)) {
+            Some(Outcome::Retry)
+        } else if self.invalid.iter().any(|r| r.is_match(text)) {
+            Some(Outcome::Invalid)
+        } else if self.accepted.iter().any(|r| r.is_match(text)) {
+            Some(Outcome::Accepted)
+        } else {
+            None
         }
     }
 }
@@ -127,6 +235,60 @@ fn config() -> Config {
                 .long("single-run")
                 .help("Run the application a single time."),
         )
+        .arg(
+            Arg::with_name("import")
+                .short("I")
+                .long("import")
+                .value_name("PATH")
+                .help("Import newline-delimited JSON flags from a file or - for stdin")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("retry_base")
+                .short("r")
+                .long("retry-base")
+                .value_name("SECONDS")
+                .help("Base backoff in seconds for requeued flags (doubles each retry)")
+                .takes_value(true)
+                .default_value("5"),
+        )
+        .arg(
+            Arg::with_name("protocol")
+                .short("p")
+                .long("protocol")
+                .value_name("PROTOCOL")
+                .help("Submission backend to use")
+                .takes_value(true)
+                .possible_values(&["form", "batch-json"])
+                .default_value("form"),
+        )
+        .arg(
+            Arg::with_name("accept_pattern")
+                .long("accept-pattern")
+                .value_name("REGEX")
+                .help("Response pattern classifying a flag as accepted (repeatable)")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("invalid_pattern")
+                .long("invalid-pattern")
+                .value_name("REGEX")
+                .help("Response pattern classifying a flag as permanently invalid (repeatable)")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("retry_pattern")
+                .long("retry-pattern")
+                .value_name("REGEX")
+                .help("Response pattern classifying a flag as retryable (repeatable)")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
         .get_matches();
 
     if matches.is_present("config") {
@@ -171,6 +333,25 @@ fn config() -> Config {
         if matches.is_present("single_run") {
             config.single_run = Some(true);
         }
+        if matches.is_present("import") {
+            config.import = Some(String::from(matches.value_of("import").unwrap()));
+        }
+        if matches.occurrences_of("retry_base") != 0 {
+            config.retry_base = value_t_or_exit!(matches.value_of("retry_base"), u32);
+        }
+        if matches.occurrences_of("protocol") != 0 {
+            config.protocol = parse_protocol(matches.value_of("protocol").unwrap());
+        }
+        // CLI pattern flags override the matching config-file list when present
+        if matches.is_present("accept_pattern") {
+            config.classification.accepted = arg_values(&matches, "accept_pattern");
+        }
+        if matches.is_present("invalid_pattern") {
+            config.classification.invalid = arg_values(&matches, "invalid_pattern");
+        }
+        if matches.is_present("retry_pattern") {
+            config.classification.retry = arg_values(&matches, "retry_pattern");
+        }
 
         return config;
     }
@@ -184,6 +365,32 @@ fn config() -> Config {
         check_interval: value_t_or_exit!(matches.value_of("check_interval"), u8),
         flags_quota: value_t_or_exit!(matches.value_of("flags_quota"), u8),
         single_run: Some(matches.is_present("single_run")),
+        import: matches.value_of("import").map(String::from),
+        retry_base: value_t_or_exit!(matches.value_of("retry_base"), u32),
+        classification: Classification {
+            accepted: arg_values(&matches, "accept_pattern"),
+            invalid: arg_values(&matches, "invalid_pattern"),
+            retry: arg_values(&matches, "retry_pattern"),
+        },
+        protocol: parse_protocol(matches.value_of("protocol").unwrap()),
+    }
+}
+
+// Collect all values of a repeatable argument into an owned Vec.
+fn arg_values(matches: &clap::ArgMatches<'_>, name: &str) -> Vec<String> {
+    matches
+        .values_of(name)
+        .map(|values| values.map(String::from).collect())
+        .unwrap_or_default()
+}
+
+// Map the `--protocol` argument to its `Protocol` variant. clap restricts the
+// allowed values, so anything else is a programmer error.
+fn parse_protocol(value: &str) -> Protocol {
+    match value {
+        "form" => Protocol::Form,
+        "batch-json" => Protocol::BatchJson,
+        other => panic!("unknown protocol {}", other),
     }
 }
 
@@ -195,24 +402,31 @@ fn read_config_file(path: &str) -> std::io::Result<String> {
 }
 
 async fn send_single_flag(
+    client: &Client,
     server_url: &str,
     team_token: &str,
     flag: &Flag,
 ) -> Result<Response, reqwest::Error> {
     println!("[SEND] flag: {} group: {}", flag.flag, flag.group);
-    let client = Client::new();
-    // Send team token and flag as post request
+    // Send team token and flag as post request on the shared client
     let parameters = [("team_token", team_token), ("flag", &flag.flag)];
     let res = client.post(server_url).form(&parameters).send().await?;
     Ok(res)
 }
 
-async fn check_response(res: Response) -> bool {
-    // Check if status is success and if response tells that flag is invalid (false positives)
+async fn check_response(res: Response, matcher: &Matcher) -> Outcome {
+    // Check if status is success and classify the body against the configured
+    // patterns, falling back to the legacy "contains invalid" heuristic.
     if res.status().is_success() {
         match res.text().await {
             Ok(text) => {
-                return !text.contains("invalid");
+                return matcher.classify(&text).unwrap_or_else(|| {
+                    if text.contains("invalid") {
+                        Outcome::Invalid
+                    } else {
+                        Outcome::Accepted
+                    }
+                });
             }
             Err(err) => {
                 eprintln!("[ERROR][CHECK] {}", err);
@@ -224,14 +438,116 @@ async fn check_response(res: Response) -> bool {
             res.status()
         );
     }
-    false
+    // A transport/HTTP failure is worth retrying rather than discarding
+    Outcome::Retry
+}
+
+// Commit a classified outcome for a single flag to the appropriate set.
+fn route_outcome(
+    outcome: Outcome,
+    id: i64,
+    flag: &str,
+    sent_set: &Arc<Mutex<HashSet<i64>>>,
+    invalid_set: &Arc<Mutex<HashSet<i64>>>,
+    retry_set: &Arc<Mutex<HashSet<i64>>>,
+) {
+    match outcome {
+        Outcome::Accepted => {
+            println!("[SENT] Flag sent flag: {}", flag);
+            sent_set.lock().unwrap().insert(id);
+        }
+        Outcome::Retry => {
+            println!("[RETRY] Server asked to retry flag {}, requeuing", flag);
+            retry_set.lock().unwrap().insert(id);
+        }
+        Outcome::Invalid => {
+            println!(
+                "[ERROR][RESPONSE] Server responded unsuccessful for flag {}",
+                flag
+            );
+            invalid_set.lock().unwrap().insert(id);
+        }
+    }
+}
+
+// Submit a whole chunk of flags in a single JSON array POST and distribute the
+// per-flag verdict array back into the sets by matching on flag value.
+async fn submit_batch(
+    client: &Client,
+    config: &Config,
+    matcher: &Matcher,
+    flags: &[Arc<Flag>],
+    sent_set: &Arc<Mutex<HashSet<i64>>>,
+    invalid_set: &Arc<Mutex<HashSet<i64>>>,
+    retry_set: &Arc<Mutex<HashSet<i64>>>,
+) {
+    // Map flag value back to its id so verdicts can be matched to rows
+    let mut by_flag: HashMap<&str, i64> = HashMap::with_capacity(flags.len());
+    let body: Vec<_> = flags
+        .iter()
+        .map(|flag| {
+            by_flag.insert(flag.flag.as_str(), flag.id);
+            serde_json::json!({ "flag": flag.flag })
+        })
+        .collect();
+    println!("[SEND] Batch of {} flags", flags.len());
+
+    let res = match client
+        .post(&config.server_url)
+        .header("X-Team-Token", &config.team_token)
+        .json(&body)
+        .send()
+        .await
+    {
+        Ok(res) => res,
+        Err(err) => {
+            eprintln!("[ERROR][SEND] {}", err);
+            return;
+        }
+    };
+    if !res.status().is_success() {
+        eprintln!(
+            "[ERROR][CHECK] Response not successful, status code {}",
+            res.status()
+        );
+        return;
+    }
+    let verdicts: Vec<BatchVerdict> = match res.json().await {
+        Ok(verdicts) => verdicts,
+        Err(err) => {
+            eprintln!("[ERROR][CHECK] {}", err);
+            return;
+        }
+    };
+    // Flags absent from the verdict array stay unsent and are retried next tick
+    for verdict in verdicts {
+        match by_flag.get(verdict.flag.as_str()) {
+            Some(&id) => {
+                let outcome = matcher.classify(&verdict.msg).unwrap_or_else(|| {
+                    if verdict.msg.contains("invalid") {
+                        Outcome::Invalid
+                    } else {
+                        Outcome::Accepted
+                    }
+                });
+                route_outcome(outcome, id, &verdict.flag, sent_set, invalid_set, retry_set);
+            }
+            None => eprintln!(
+                "[ERROR][RESPONSE] Unknown flag in batch verdict: {}",
+                verdict.flag
+            ),
+        }
+    }
 }
 
 async fn send_flags_with_throttle(
+    client: &Arc<Client>,
     sent_set: &Arc<Mutex<HashSet<i64>>>,
     invalid_set: &Arc<Mutex<HashSet<i64>>>,
+    retry_set: &Arc<Mutex<HashSet<i64>>>,
     flags: &Vec<Arc<Flag>>,
     config: &Arc<Config>,
+    matcher: &Arc<Matcher>,
 ) -> Vec<JoinHandle<()>> {
     // Returned vec of all the task spawned
     let mut joins: Vec<JoinHandle<()>> = Vec::with_capacity(flags.len());
@@ -241,33 +557,63 @@ async fn send_flags_with_throttle(
     // Send FLAGS_PER_SECOND before waiting the throttle time
     for chunk in flags.chunks(config.flags_quota as usize) {
         interval.tick().await;
-        for flag in chunk {
-            let sent_set = Arc::clone(sent_set);
-            let invalid_set = Arc::clone(invalid_set);
-            let flag = Arc::clone(flag);
-            let config = Arc::clone(config);
-            let join = spawn(async move {
-                match send_single_flag(&config.server_url, &config.team_token, &flag).await {
-                    Ok(res) => {
-                        // If flag was sent successfully we add it to the set to
-                        // be set as sent afterwards
-                        if check_response(res).await {
-                            println!("[SENT] Flag sent flag: {} group: {}", flag.flag, flag.group);
-                            let mut hash_set = sent_set.lock().unwrap();
-                            hash_set.insert(flag.id);
-                        } else {
-                            println!(
-                                "[ERROR][RESPONSE] Server responded unsuccessful for flag {}",
-                                flag.flag
-                            );
-                            let mut hash_set = invalid_set.lock().unwrap();
-                            hash_set.insert(flag.id);
+        match config.protocol {
+            // One form POST per flag on the shared client
+            Protocol::Form => {
+                for flag in chunk {
+                    let client = Arc::clone(client);
+                    let sent_set = Arc::clone(sent_set);
+                    let invalid_set = Arc::clone(invalid_set);
+                    let retry_set = Arc::clone(retry_set);
+                    let flag = Arc::clone(flag);
+                    let config = Arc::clone(config);
+                    let matcher = Arc::clone(matcher);
+                    let join = spawn(async move {
+                        match send_single_flag(&client, &config.server_url, &config.team_token, &flag)
+                            .await
+                        {
+                            Ok(res) => {
+                                // Classify the response and route the flag to the
+                                // set it should be committed to afterwards
+                                let outcome = check_response(res, &matcher).await;
+                                route_outcome(
+                                    outcome,
+                                    flag.id,
+                                    &flag.flag,
+                                    &sent_set,
+                                    &invalid_set,
+                                    &retry_set,
+                                );
+                            }
+                            Err(err) => eprintln!("[ERROR][SEND] {}", err),
                         }
-                    }
-                    Err(err) => eprintln!("[ERROR][SEND] {}", err),
+                    });
+                    joins.push(join);
                 }
-            });
-            joins.push(join);
+            }
+            // One JSON array POST for the whole chunk
+            Protocol::BatchJson => {
+                let client = Arc::clone(client);
+                let sent_set = Arc::clone(sent_set);
+                let invalid_set = Arc::clone(invalid_set);
+                let retry_set = Arc::clone(retry_set);
+                let config = Arc::clone(config);
+                let matcher = Arc::clone(matcher);
+                let chunk: Vec<Arc<Flag>> = chunk.to_vec();
+                let join = spawn(async move {
+                    submit_batch(
+                        &client,
+                        &config,
+                        &matcher,
+                        &chunk,
+                        &sent_set,
+                        &invalid_set,
+                        &retry_set,
+                    )
+                    .await;
+                });
+                joins.push(join);
+            }
         }
     }
     // Return join for the tasks
@@ -275,15 +621,27 @@ async fn send_flags_with_throttle(
 }
 
 async fn run<T: Error, U: database::Database<T>>(
-    db: &mut U,
+    db: &U,
+    client: &Arc<Client>,
     config: &Arc<Config>,
     sent_set: &Arc<Mutex<HashSet<i64>>>,
     invalid_set: &Arc<Mutex<HashSet<i64>>>,
+    retry_set: &Arc<Mutex<HashSet<i64>>>,
+    matcher: &Arc<Matcher>,
 ) {
-    match db.get_unsent_flags() {
+    match db.get_unsent_flags().await {
         Ok(flags) => {
             // Send all the flags and wait for all threads to finish
-            let joins = send_flags_with_throttle(sent_set, invalid_set, &flags, config).await;
+            let joins = send_flags_with_throttle(
+                client,
+                sent_set,
+                invalid_set,
+                retry_set,
+                &flags,
+                config,
+                matcher,
+            )
+            .await;
             for join in joins {
                 if let Err(err) = join.await {
                     eprintln!("[ERROR][JOIN] {}", err);
@@ -292,31 +650,147 @@ async fn run<T: Error, U: database::Database<T>>(
         }
         Err(err) => eprintln!("[ERROR][GET] {}", err),
     }
+    // Drain each set into a local Vec inside a short lock scope so the mutex
+    // guard is never held across the following DB awaits.
+    let sent: Vec<i64> = sent_set.lock().unwrap().drain().collect();
+    let invalid: Vec<i64> = invalid_set.lock().unwrap().drain().collect();
+    let retry: Vec<i64> = retry_set.lock().unwrap().drain().collect();
+
     // Update all the sent flags
-    let mut hash_set = sent_set.lock().unwrap();
-    if let Err(err) = db.set_sent_flags(&mut hash_set) {
+    if let Err(err) = db.set_sent_flags(&sent).await {
         eprintln!("[ERROR][SET][SENT] {}", err);
     }
     // Update all the invalid flags
-    let mut hash_set = invalid_set.lock().unwrap();
-    if let Err(err) = db.set_invalid_flags(&mut hash_set) {
+    if let Err(err) = db.set_invalid_flags(&invalid).await {
         eprintln!("[ERROR][SET][INVALID] {}", err);
     }
+    // Requeue all the retryable flags with exponential backoff
+    if let Err(err) = db.set_retry_flags(&retry, config.retry_base).await {
+        eprintln!("[ERROR][SET][RETRY] {}", err);
+    }
 }
 
 async fn main_loop<T: Error, U: database::Database<T>>(
-    db: &mut U,
+    db: &U,
+    client: &Arc<Client>,
     config: &Arc<Config>,
     sent_set: &Arc<Mutex<HashSet<i64>>>,
     invalid_set: &Arc<Mutex<HashSet<i64>>>,
+    retry_set: &Arc<Mutex<HashSet<i64>>>,
+    matcher: &Arc<Matcher>,
+    mut notify: Option<mpsc::Receiver<()>>,
 ) {
-    // Interval for checking flags to sent
+    // Interval for checking flags to sent, kept as a fallback safety net even
+    // when the Postgres LISTEN/NOTIFY listener is driving instant submissions.
     let mut interval = interval(Duration::from_secs(config.check_interval as u64));
 
     loop {
-        interval.tick().await;
-        run(db, config, sent_set, invalid_set).await;
+        // Set when the listener task has gone away so we can fall back to
+        // interval-only polling after releasing the mutable borrow below.
+        let mut listener_closed = false;
+        match notify {
+            // Postgres backend: run on the interval tick or as soon as a new
+            // flag is notified, whichever comes first.
+            Some(ref mut rx) => {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    received = rx.recv() => match received {
+                        Some(()) => {
+                            println!("[NOTIFY] Triggering submission from notification");
+                        }
+                        // Listener task died (DB restart, network blip): drop to
+                        // the interval safety net instead of spinning on a closed
+                        // channel that always resolves immediately.
+                        None => {
+                            eprintln!(
+                                "[ERROR][LISTEN] Listener stopped, falling back to interval polling"
+                            );
+                            listener_closed = true;
+                        }
+                    },
+                }
+            }
+            // No listener (SQLite, or listener setup failed): interval only.
+            None => {
+                interval.tick().await;
+            }
+        }
+        if listener_closed {
+            notify = None;
+        }
+        run(db, client, config, sent_set, invalid_set, retry_set, matcher).await;
+    }
+}
+
+async fn flush_import<T: Error, U: database::Database<T>>(
+    db: &U,
+    batch: &mut Vec<ImportRecord>,
+    inserted: &mut usize,
+    skipped: &mut usize,
+) {
+    // Each batch is one transaction; duplicate flags are skipped cheaply by the
+    // ON CONFLICT / OR IGNORE clause, so re-running exploits is idempotent.
+    match db.import_flags(batch).await {
+        Ok(count) => {
+            *inserted += count;
+            *skipped += batch.len() - count;
+        }
+        Err(err) => eprintln!("[ERROR][IMPORT] {}", err),
     }
+    batch.clear();
+}
+
+async fn run_import<T: Error, U: database::Database<T>>(db: &U, path: &str) {
+    // Max records inserted per transaction
+    const BATCH_SIZE: usize = 1000;
+
+    // Parse JSONL off the runtime in a reader thread, streaming records to the
+    // writer over a channel so parsing and DB writes overlap.
+    let (tx, rx) = std_mpsc::channel::<ImportRecord>();
+    let path = String::from(path);
+    let reader = std::thread::spawn(move || -> std::io::Result<()> {
+        let input: Box<dyn BufRead> = if path == "-" {
+            Box::new(BufReader::new(std::io::stdin()))
+        } else {
+            Box::new(BufReader::new(File::open(&path)?))
+        };
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<ImportRecord>(&line) {
+                Ok(record) => {
+                    // Receiver gone means the writer bailed, stop reading
+                    if tx.send(record).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => eprintln!("[ERROR][IMPORT] Skipping malformed line: {}", err),
+            }
+        }
+        Ok(())
+    });
+
+    let mut batch: Vec<ImportRecord> = Vec::with_capacity(BATCH_SIZE);
+    let mut inserted = 0usize;
+    let mut skipped = 0usize;
+    for record in rx {
+        batch.push(record);
+        if batch.len() >= BATCH_SIZE {
+            flush_import(db, &mut batch, &mut inserted, &mut skipped).await;
+        }
+    }
+    if !batch.is_empty() {
+        flush_import(db, &mut batch, &mut inserted, &mut skipped).await;
+    }
+    if let Err(err) = reader.join().expect("import reader thread panicked") {
+        eprintln!("[ERROR][IMPORT] {}", err);
+    }
+    println!(
+        "[IMPORT] Inserted {} flags, skipped {} duplicates",
+        inserted, skipped
+    );
 }
 
 #[tokio::main]
@@ -325,45 +799,130 @@ async fn main() {
     // Set of all the sent flags
     let sent_set: Arc<Mutex<HashSet<i64>>> = Arc::new(Mutex::new(HashSet::new()));
     let invalid_set: Arc<Mutex<HashSet<i64>>> = Arc::new(Mutex::new(HashSet::new()));
+    // Set of all the flags to requeue for a later attempt
+    let retry_set: Arc<Mutex<HashSet<i64>>> = Arc::new(Mutex::new(HashSet::new()));
     // Configuration to share across threads, only for read
     let arc_config = Arc::new(config.clone());
+    // Compiled response classifier shared across send tasks
+    let matcher = Arc::new(Matcher::build(&config.classification).unwrap_or_else(|err| {
+        eprintln!("[ERROR][CONFIG] Invalid classification pattern: {}", err);
+        panic!("main");
+    }));
+    // Single HTTP client reused across all submissions to avoid connection churn
+    let client = Arc::new(Client::new());
 
     // Select the database type, default to sqlite
     if let Some(sqlite) = config.sqlite {
-        // Database connection, with appropriate functions
-        let mut db = Box::new(database::Sqlite {
-            db: rusqlite::Connection::open(&sqlite).unwrap(),
+        // Pooled, async SQLite connection manager
+        let db = database::Sqlite::open(&sqlite).unwrap_or_else(|err| {
+            eprintln!("[ERROR][SETUP] {}", err);
+            panic!("main");
         });
 
         // Setup the database, creating necessary tables
-        if let Err(err) = db.setup() {
+        if let Err(err) = db.setup().await {
             eprintln!("[ERROR][SETUP] {}", err);
             panic!("main");
         }
 
-        // Select the run mode, default to a loop
+        // Bulk import mode short-circuits the submission loop
+        if let Some(import) = &config.import {
+            run_import(&db, import).await;
+            return;
+        }
+
+        // Select the run mode, default to a loop. SQLite has no LISTEN/NOTIFY.
         if !config.single_run.unwrap_or(false) {
-            main_loop(&mut (*db), &arc_config, &sent_set, &invalid_set).await;
+            main_loop(&db, &client, &arc_config, &sent_set, &invalid_set, &retry_set, &matcher, None).await;
         } else {
-            run(&mut (*db), &arc_config, &sent_set, &invalid_set).await;
+            run(&db, &client, &arc_config, &sent_set, &invalid_set, &retry_set, &matcher).await;
         }
     } else {
-        // Database connection, with appropriate functions
-        let mut db = Box::new(database::Postgres {
-            db: postgres::Client::connect(&config.postgres.unwrap(), postgres::NoTls).unwrap(),
+        // Pooled, async PostgreSQL connection manager
+        let db = database::Postgres::connect(&config.postgres.unwrap()).unwrap_or_else(|err| {
+            eprintln!("[ERROR][SETUP] {}", err);
+            panic!("main");
         });
 
         // Setup the database, creating necessary tables
-        if let Err(err) = db.setup() {
+        if let Err(err) = db.setup().await {
             eprintln!("[ERROR][SETUP] {}", err);
             panic!("main");
         }
 
+        // Bulk import mode short-circuits the submission loop
+        if let Some(import) = &config.import {
+            run_import(&db, import).await;
+            return;
+        }
+
         // Select the run mode, default to a loop
         if !config.single_run.unwrap_or(false) {
-            main_loop(&mut (*db), &arc_config, &sent_set, &invalid_set).await;
+            // Start the LISTEN/NOTIFY listener for near-real-time submission;
+            // fall back to the interval-only loop if it cannot be established.
+            let notify = match db.listen().await {
+                Ok(rx) => Some(rx),
+                Err(err) => {
+                    eprintln!("[ERROR][LISTEN] {}", err);
+                    None
+                }
+            };
+            main_loop(&db, &client, &arc_config, &sent_set, &invalid_set, &retry_set, &matcher, notify).await;
         } else {
-            run(&mut (*db), &arc_config, &sent_set, &invalid_set).await;
+            run(&db, &client, &arc_config, &sent_set, &invalid_set, &retry_set, &matcher).await;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classify_precedence() {
+        let matcher = Matcher::build(&Classification {
+            accepted: vec!["ACCEPTED".to_string()],
+            invalid: vec!["OLD".to_string()],
+            retry: vec!["RATELIMIT".to_string()],
+        })
+        .unwrap();
+        assert_eq!(matcher.classify("RATELIMIT"), Some(Outcome::Retry));
+        assert_eq!(matcher.classify("OLD"), Some(Outcome::Invalid));
+        assert_eq!(matcher.classify("ACCEPTED"), Some(Outcome::Accepted));
+        // Nothing configured matches: fall back is left to the caller
+        assert_eq!(matcher.classify("NOP"), None);
+    }
+
+    #[test]
+    fn classify_retry_wins_over_invalid_and_accepted() {
+        // When a body matches several lists, retry takes precedence so a flag is
+        // requeued rather than discarded.
+        let matcher = Matcher::build(&Classification {
+            accepted: vec!["flag".to_string()],
+            invalid: vec!["flag".to_string()],
+            retry: vec!["flag".to_string()],
+        })
+        .unwrap();
+        assert_eq!(matcher.classify("flag"), Some(Outcome::Retry));
+    }
+
+    #[test]
+    fn backoff_doubles_each_retry() {
+        // Mirror of the `retry_base * 2^retries` SQL to pin the intended schedule.
+        fn backoff_seconds(base: u64, retries: u32) -> u64 {
+            base * (1u64 << retries)
+        }
+        assert_eq!(backoff_seconds(5, 0), 5);
+        assert_eq!(backoff_seconds(5, 1), 10);
+        assert_eq!(backoff_seconds(5, 2), 20);
+        assert_eq!(backoff_seconds(5, 3), 40);
+    }
+
+    #[test]
+    fn import_record_parsing() {
+        let record: ImportRecord =
+            serde_json::from_str(r#"{"flag":"CTF{abc}","group":3}"#).unwrap();
+        assert_eq!(record.flag, "CTF{abc}");
+        assert_eq!(record.group, 3);
+    }
+}